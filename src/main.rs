@@ -2,59 +2,117 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::{fmt, io, process};
 
-use csv;
 use rand::{self, distributions::Distribution, distributions::Uniform, Rng};
-use serde::Deserialize;
-
-#[derive(Debug, Deserialize)]
-struct Badger {
-    pub name: String,
-    pub gender: String,
-    pub discipline: String,
-    pub seniority: String,
-    pub client: String,
-    pub team: String,
+use rayon::prelude::*;
+
+// A person record read from CSV: `id` is the first column, used only for
+// display, and every other column is carried as a balanced attribute by
+// name instead of being welded into a fixed struct.
+#[derive(Debug, Clone)]
+struct Record {
+    id: String,
+    attributes: Vec<(String, String)>,
 }
 
-fn read_badgers() -> Result<Vec<Badger>, Box<dyn Error>> {
+fn read_records() -> Result<Vec<Record>, Box<dyn Error>> {
     let mut reader = csv::Reader::from_reader(io::stdin());
-    let badgers = reader.deserialize().collect::<Result<Vec<Badger>, _>>()?;
+    let headers = reader.headers()?.clone();
+
+    let mut records = Vec::new();
+
+    for result in reader.records() {
+        let row = result?;
+
+        let mut id = String::new();
+        let mut attributes = Vec::new();
+
+        for (i, column) in headers.iter().enumerate() {
+            let value = row.get(i).unwrap_or("").to_string();
+
+            if i == 0 {
+                id = value;
+            } else {
+                attributes.push((column.to_string(), value));
+            }
+        }
+
+        records.push(Record { id, attributes });
+    }
 
-    Ok(badgers)
+    Ok(records)
 }
 
-impl fmt::Display for Badger {
+impl fmt::Display for Record {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{} ({}, {}, {}, {}, {})",
-            self.name, self.gender, self.discipline, self.seniority, self.client, self.team
-        )?;
+        write!(f, "{} (", self.id)?;
+
+        for (i, (_, value)) in self.attributes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", value)?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+// Data-driven attribute schema: every CSV column but the id is a balanced
+// attribute, with a configurable weight in the fitness calculation.
+struct Schema {
+    weights: HashMap<String, f64>,
+}
+
+impl Schema {
+    fn new(columns: &[String]) -> Self {
+        Self {
+            weights: columns.iter().map(|column| (column.clone(), 1.0)).collect(),
+        }
+    }
 
-        Ok(())
+    fn weight(&self, column: &str) -> f64 {
+        self.weights.get(column).copied().unwrap_or(1.0)
     }
 }
 
 // Genetic optimization
 
-fn mutate<D>(individual: &Vec<usize>, dist: D) -> Vec<usize>
-where
-    D: Distribution<usize>,
-{
-    let mut rng = rand::thread_rng();
+// A genotype the GA engine can breed without knowing its internal
+// representation: random individuals, point mutation and crossover are
+// supplied by the implementor instead of being welded into `Generation`.
+trait Genotype: Clone + Send + Sync {
+    // A new random individual with the same "shape" as `self` (e.g. the
+    // same length and number of possible group values).
+    fn random(&self, rng: &mut impl Rng) -> Self;
+    // With probability `rate`, perturbs the individual.
+    fn mutate(&self, rate: f64, rng: &mut impl Rng) -> Self;
+    // Single-point crossover: genes up to a random cut point come from
+    // `self`, the rest from `other`.
+    fn crossover(&self, other: &Self, rng: &mut impl Rng) -> Self;
+    // Uniform crossover: each gene is taken independently from `self` or
+    // `other` with probability 0.5.
+    fn crossover_uniform(&self, other: &Self, rng: &mut impl Rng) -> Self;
+}
+
+// Point mutation: reassigns one random person to a random group.
+fn point_mutation<D: Distribution<usize>, R: Rng>(
+    individual: &[usize],
+    dist: D,
+    rng: &mut R,
+) -> Vec<usize> {
     let idx = rng.gen_range(0, individual.len());
     let value = rng.sample(dist);
 
-    let mut result = individual.clone();
+    let mut result = individual.to_owned();
     result[idx] = value;
 
     result
 }
 
-fn cross_over(mother: &Vec<usize>, father: &Vec<usize>) -> Vec<usize> {
+// Single-point crossover: everything up to a random cut point comes from
+// `mother`, the rest from `father`.
+fn single_point_crossover<R: Rng>(mother: &[usize], father: &[usize], rng: &mut R) -> Vec<usize> {
     let mut child = Vec::new();
-
-    let mut rng = rand::thread_rng();
     let crossover_point = rng.gen_range(0, mother.len());
 
     child.extend_from_slice(&mother[0..crossover_point]);
@@ -63,67 +121,362 @@ fn cross_over(mother: &Vec<usize>, father: &Vec<usize>) -> Vec<usize> {
     child
 }
 
-struct Generation<F: Fn(&Vec<usize>) -> f64> {
-    pub population: Vec<Vec<usize>>,
-    pub ngroups: usize,
+// The original genotype: one group index per person.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct GroupAssignment {
+    groups: Vec<usize>,
+    ngroups: usize,
+}
+
+impl GroupAssignment {
+    fn new(groups: Vec<usize>, ngroups: usize) -> Self {
+        Self { groups, ngroups }
+    }
+}
+
+impl Genotype for GroupAssignment {
+    fn random(&self, rng: &mut impl Rng) -> Self {
+        let dist = Uniform::new(0, self.ngroups);
+
+        GroupAssignment {
+            groups: rng.sample_iter(dist).take(self.groups.len()).collect(),
+            ngroups: self.ngroups,
+        }
+    }
+
+    fn mutate(&self, rate: f64, rng: &mut impl Rng) -> Self {
+        if !rng.gen_bool(rate) {
+            return self.clone();
+        }
+
+        GroupAssignment {
+            groups: point_mutation(&self.groups, Uniform::new(0, self.ngroups), rng),
+            ngroups: self.ngroups,
+        }
+    }
+
+    fn crossover(&self, other: &Self, rng: &mut impl Rng) -> Self {
+        GroupAssignment {
+            groups: single_point_crossover(&self.groups, &other.groups, rng),
+            ngroups: self.ngroups,
+        }
+    }
+
+    fn crossover_uniform(&self, other: &Self, rng: &mut impl Rng) -> Self {
+        let groups = self
+            .groups
+            .iter()
+            .zip(&other.groups)
+            .map(|(mother, father)| if rng.gen_bool(0.5) { *mother } else { *father })
+            .collect();
+
+        GroupAssignment {
+            groups,
+            ngroups: self.ngroups,
+        }
+    }
+}
+
+// Picks one parent out of a scored population. `scored` is sorted fittest
+// (lowest score) first, but implementations are free to ignore the order.
+trait SelectionMethod<G> {
+    fn select<'a>(&self, scored: &'a [(f64, G)], rng: &mut impl Rng) -> &'a G;
+}
+
+// The original hard-coded behaviour: keep the top `survival_rate` fraction
+// and pick parents uniformly from that slice.
+struct Truncation {
+    pub survival_rate: f64,
+}
+
+impl<G> SelectionMethod<G> for Truncation {
+    fn select<'a>(&self, scored: &'a [(f64, G)], rng: &mut impl Rng) -> &'a G {
+        let survivor_count = ((scored.len() as f64 * self.survival_rate).floor() as usize).max(1);
+
+        &scored[rng.gen_range(0, survivor_count)].1
+    }
+}
+
+// Repeatedly draws `size` individuals from the whole population and keeps
+// the fittest of them. Worse-than-median individuals occasionally get to
+// reproduce, which preserves diversity better than hard truncation.
+//
+// `main` currently wires up `Truncation`; this is here for callers who
+// want to swap in tournament selection instead.
+#[allow(dead_code)]
+struct Tournament {
+    pub size: usize,
+}
+
+impl<G> SelectionMethod<G> for Tournament {
+    fn select<'a>(&self, scored: &'a [(f64, G)], rng: &mut impl Rng) -> &'a G {
+        let mut best = &scored[rng.gen_range(0, scored.len())];
+
+        for _ in 1..self.size {
+            let candidate = &scored[rng.gen_range(0, scored.len())];
+            if candidate.0 < best.0 {
+                best = candidate;
+            }
+        }
+
+        &best.1
+    }
+}
+
+// Fitness-proportionate ("roulette wheel") selection. Lower fitness is
+// better, so each individual's slice of the wheel is proportional to the
+// inverse of its score rather than the score itself.
+//
+// Also unused by `main` for now; see `Tournament` above.
+#[allow(dead_code)]
+struct Roulette;
+
+impl<G> SelectionMethod<G> for Roulette {
+    fn select<'a>(&self, scored: &'a [(f64, G)], rng: &mut impl Rng) -> &'a G {
+        const EPSILON: f64 = 1e-9;
+
+        let weights: Vec<f64> = scored.iter().map(|(score, _)| 1.0 / (score + EPSILON)).collect();
+        let total: f64 = weights.iter().sum();
+
+        let pick = rng.gen_range(0.0, total);
+        let mut acc = 0.0;
+
+        for (weight, (_, individual)) in weights.iter().zip(scored) {
+            acc += weight;
+            if acc >= pick {
+                return individual;
+            }
+        }
+
+        &scored.last().unwrap().1
+    }
+}
+
+// Hard constraints, modeled separately from fitness: "these two must be on
+// the same table", "these two must never be" and "every table holds
+// N people". `validate` returns the number of violated constraints (0 =
+// feasible); during selection any individual with `validate > 0` is
+// treated as strictly worse than any feasible one, so the search is
+// pushed into the feasible region before the weighted-sum balance is
+// optimized.
+trait Validate<G> {
+    fn validate(&self, genotype: &G) -> u64;
+    // Reassigns people out of over/under-full groups (as introduced by
+    // mutation or crossover) to push a genotype back towards feasibility.
+    fn repair(&self, genotype: &mut G);
+}
+
+struct Constraints {
+    // Pairs of badger indices that must end up in the same group.
+    pub together: Vec<(usize, usize)>,
+    // Pairs of badger indices that must never end up in the same group.
+    pub apart: Vec<(usize, usize)>,
+    // Every group must hold within `size_tolerance` of this many people.
+    pub group_size: usize,
+    pub size_tolerance: usize,
+}
+
+impl Constraints {
+    fn new(group_size: usize, size_tolerance: usize) -> Self {
+        Self {
+            together: Vec::new(),
+            apart: Vec::new(),
+            group_size,
+            size_tolerance,
+        }
+    }
+
+    fn group_sizes(&self, groups: &[usize]) -> HashMap<usize, usize> {
+        let mut sizes = HashMap::new();
+        for &group in groups {
+            *sizes.entry(group).or_insert(0) += 1;
+        }
+        sizes
+    }
+}
+
+impl Validate<GroupAssignment> for Constraints {
+    fn validate(&self, genotype: &GroupAssignment) -> u64 {
+        let solution = &genotype.groups;
+        let mut violations = 0u64;
+
+        for &(a, b) in &self.together {
+            if solution[a] != solution[b] {
+                violations += 1;
+            }
+        }
+
+        for &(a, b) in &self.apart {
+            if solution[a] == solution[b] {
+                violations += 1;
+            }
+        }
+
+        for &count in self.group_sizes(solution).values() {
+            let deviation = (count as i64 - self.group_size as i64).unsigned_abs();
+            if deviation > self.size_tolerance as u64 {
+                violations += deviation - self.size_tolerance as u64;
+            }
+        }
+
+        violations
+    }
+
+    fn repair(&self, genotype: &mut GroupAssignment) {
+        let ngroups = genotype.ngroups;
+
+        loop {
+            let sizes = self.group_sizes(&genotype.groups);
+            let min_size = self.group_size.saturating_sub(self.size_tolerance);
+            let max_size = self.group_size + self.size_tolerance;
+
+            let overfull = (0..ngroups).find(|g| sizes.get(g).copied().unwrap_or(0) > max_size);
+            let underfull = (0..ngroups).find(|g| sizes.get(g).copied().unwrap_or(0) < min_size);
+
+            match (overfull, underfull) {
+                (Some(from), Some(to)) => match genotype.groups.iter().position(|&g| g == from) {
+                    Some(idx) => genotype.groups[idx] = to,
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+    }
+}
+
+// Selects which crossover operator `Generation::next_gen` uses. Single
+// point is the classic operator; uniform crossover decides each gene
+// independently and mixes the parents more thoroughly.
+enum CrossoverStrategy {
+    SinglePoint,
+    // Unused by `main`, which keeps `SinglePoint`; here for callers who
+    // want more thorough mixing than a single cut point gives.
+    #[allow(dead_code)]
+    Uniform,
+}
+
+struct Generation<G: Genotype + Eq + std::hash::Hash, F: Fn(&G) -> f64, S: SelectionMethod<G>, C: Validate<G> + Sync> {
+    pub population: Vec<G>,
     pub crossover_rate: f64,
     pub mutation_rate: f64,
-    pub survival_rate: f64,
+    pub crossover_strategy: CrossoverStrategy,
     pub fitness: F,
+    pub selection: S,
+    pub constraints: C,
+    // Adaptive mutation rate: `mutation_rate` is nudged towards
+    // `mutation_ceiling` while progress is stalled and decayed back
+    // towards `mutation_floor` while fitness is improving quickly.
+    pub mutation_floor: f64,
+    pub mutation_ceiling: f64,
+    // Stop criteria, checked by `track`: converged once the best fitness
+    // hasn't improved by more than `convergence_epsilon` over the last
+    // `convergence_window` generations, or once it reaches `target_fitness`.
+    pub convergence_window: usize,
+    pub convergence_epsilon: f64,
+    pub target_fitness: f64,
+    recent_best: Vec<f64>,
+    // Memoizes fitness by genotype so individuals that survive unchanged
+    // across generations are never re-scored. Off by default.
+    cache: Option<HashMap<G, f64>>,
 }
 
-impl<F: Fn(&Vec<usize>) -> f64> Generation<F> {
-    fn new(population_size: usize, ngroups: usize, nbadgers: usize, fitness: F) -> Self {
-        let rng = rand::thread_rng();
-
-        let dist = Uniform::new(1, ngroups);
-        let init_pop: Vec<Vec<usize>> = (0..population_size)
-            .map(|_| rng.sample_iter(dist).take(nbadgers).collect())
-            .collect();
+impl<G: Genotype + Eq + std::hash::Hash, F: Fn(&G) -> f64 + Sync, S: SelectionMethod<G>, C: Validate<G> + Sync>
+    Generation<G, F, S, C>
+{
+    // `seed` is a template individual (correct length / shape) used to
+    // build the random initial population via `Genotype::random`.
+    fn new(population_size: usize, seed: G, fitness: F, selection: S, constraints: C) -> Self {
+        let mut rng = rand::thread_rng();
+        let population: Vec<G> = (0..population_size).map(|_| seed.random(&mut rng)).collect();
 
-        assert_eq!(init_pop.len(), population_size);
+        assert_eq!(population.len(), population_size);
 
         Generation {
-            population: init_pop,
-            ngroups,
+            population,
             crossover_rate: 0.85,
             mutation_rate: 0.15,
-            survival_rate: 0.2,
-            fitness: fitness,
+            crossover_strategy: CrossoverStrategy::SinglePoint,
+            fitness,
+            selection,
+            constraints,
+            // Floor/ceiling pinned to the fixed rate and a window no run
+            // will ever fill recovers the original, non-adaptive behaviour.
+            mutation_floor: 0.15,
+            mutation_ceiling: 0.15,
+            convergence_window: usize::MAX,
+            convergence_epsilon: 0.0,
+            target_fitness: f64::NEG_INFINITY,
+            recent_best: Vec::new(),
+            cache: None,
         }
     }
 
-    pub fn next_gen(self) -> Self {
-        let size = self.population.len();
+    // Turns on fitness memoization; call once after `new`.
+    pub fn with_cache(mut self) -> Self {
+        self.cache = Some(HashMap::new());
+        self
+    }
+
+    // Records the best fitness of the current generation, adapts
+    // `mutation_rate` based on the recent slope of improvement, and
+    // reports whether a stop criterion has been met. `best` is checked
+    // against the constraints directly: a plateau or a `target_fitness`
+    // reached while the best individual is still infeasible is never
+    // reported as convergence, since its score is dominated by the
+    // constraint penalty rather than the fitness it's plateauing on.
+    pub fn track(&mut self, best_score: f64, best: &G) -> bool {
+        self.recent_best.push(best_score);
+        if self.recent_best.len() > self.convergence_window {
+            self.recent_best.remove(0);
+        }
+
+        let stalled = self.recent_best.len() == self.convergence_window
+            && self.recent_best.first().unwrap() - self.recent_best.last().unwrap()
+                <= self.convergence_epsilon;
+
+        if stalled {
+            self.mutation_rate = (self.mutation_rate * 1.2).min(self.mutation_ceiling);
+        } else {
+            self.mutation_rate = (self.mutation_rate * 0.9).max(self.mutation_floor);
+        }
+
+        let feasible = self.constraints.validate(best) == 0;
 
-        // Selection
+        feasible && (stalled || best_score <= self.target_fitness)
+    }
 
-        let fittest = self.fittest();
-        let fittest_count = fittest.len();
+    // Breeds the next generation from an already-scored population, so a
+    // generation is scored exactly once, by the caller's call to `scored`,
+    // and that one result serves both for reporting and for breeding.
+    pub fn next_gen(self, scored: &[(f64, G)]) -> Self {
+        let size = self.population.len();
 
         // Breeding
 
-        let mut population: Vec<Vec<usize>> = Vec::with_capacity(size);
+        let mut population: Vec<G> = Vec::with_capacity(size);
         let mut rng = rand::thread_rng();
 
         for _ in 0..size {
-            let mother = rng.gen_range(0, fittest_count);
+            let mother = self.selection.select(scored, &mut rng);
 
             // Cross-over
-            if rng.gen_bool(self.crossover_rate) {
-                let father = rng.gen_range(0, fittest_count);
+            let mut child = if rng.gen_bool(self.crossover_rate) {
+                let father = self.selection.select(scored, &mut rng);
 
-                population.push(cross_over(&fittest[mother], &fittest[father]));
+                match self.crossover_strategy {
+                    CrossoverStrategy::SinglePoint => mother.crossover(father, &mut rng),
+                    CrossoverStrategy::Uniform => mother.crossover_uniform(father, &mut rng),
+                }
             } else {
-                population.push(fittest[mother].clone());
-            }
+                mother.clone()
+            };
 
             // Mutation
+            child = child.mutate(self.mutation_rate, &mut rng);
 
-            if rng.gen_bool(self.mutation_rate) {
-                *population.last_mut().unwrap() =
-                    mutate(population.last().unwrap(), Uniform::new(0, self.ngroups));
-            }
+            self.constraints.repair(&mut child);
+
+            population.push(child);
         }
 
         assert_eq!(self.population.len(), population.len());
@@ -131,14 +484,59 @@ impl<F: Fn(&Vec<usize>) -> f64> Generation<F> {
         Generation { population, ..self }
     }
 
-    fn fittest(&self) -> Vec<Vec<usize>> {
-        let mut fittest = self.population.clone();
+    // Scores every individual exactly once (in parallel, consulting the
+    // cache if enabled) and returns the whole population sorted from
+    // fittest to least fit. Infeasible individuals (violating a hard
+    // constraint) always sort below every feasible one, ranked among
+    // themselves by how many constraints they violate.
+    pub fn scored(&mut self) -> Vec<(f64, G)> {
+        const INFEASIBLE_PENALTY: f64 = 1e9;
+
+        let fitness = &self.fitness;
+        let constraints = &self.constraints;
+        let cache = &self.cache;
+
+        let evaluated: Vec<(f64, f64, G)> = self
+            .population
+            .par_iter()
+            .map(|individual| {
+                let raw = cache
+                    .as_ref()
+                    .and_then(|cache| cache.get(individual))
+                    .copied()
+                    .unwrap_or_else(|| fitness(individual));
+
+                let violations = constraints.validate(individual);
+                // Infeasible individuals always sort below every feasible
+                // one (this base dwarfs any realistic raw fitness) but the
+                // `+ violations` gradient still has to be representable:
+                // `f64::MAX` is too coarse-grained to add small integers to.
+                let score = if violations > 0 {
+                    INFEASIBLE_PENALTY + violations as f64
+                } else {
+                    raw
+                };
+
+                (raw, score, individual.clone())
+            })
+            .collect();
+
+        if let Some(cache) = &mut self.cache {
+            for (raw, _, individual) in &evaluated {
+                cache.entry(individual.clone()).or_insert(*raw);
+            }
+        }
 
-        fittest.sort_by(|a, b| (self.fitness)(a).partial_cmp(&(self.fitness)(b)).unwrap());
+        let mut scored: Vec<(f64, G)> = evaluated
+            .into_iter()
+            .map(|(_, score, individual)| (score, individual))
+            .collect();
+
+        scored.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
 
-        let survivor_count = (fittest.len() as f64 * self.survival_rate).floor() as usize;
-        fittest[0..survivor_count].to_owned()
+        scored
     }
+
 }
 
 // Fitness metric calculations
@@ -197,119 +595,461 @@ where
     }
 }
 
-// Profile of a group as histograms of attributes
+// Profile of a group as histograms of attributes, one per schema column.
 #[derive(Debug)]
-struct Profile<'a> {
-    genders: Histogram<&'a String>,
-    disciplines: Histogram<&'a String>,
-    seniorities: Histogram<&'a String>,
-    clients: Histogram<&'a String>,
-    teams: Histogram<&'a String>,
+struct Profile {
+    histograms: HashMap<String, Histogram<String>>,
     pub count: f64,
 }
 
-impl<'a> Profile<'a> {
+impl Profile {
     fn new() -> Self {
         Self {
-            genders: Histogram::new(),
-            disciplines: Histogram::new(),
-            seniorities: Histogram::new(),
-            clients: Histogram::new(),
-            teams: Histogram::new(),
+            histograms: HashMap::new(),
             count: 0.0,
         }
     }
 
-    fn insert(&mut self, badger: &'a Badger) {
+    fn insert(&mut self, record: &Record) {
         self.count += 1.0;
-        self.genders.insert(&badger.gender);
-        self.disciplines.insert(&badger.discipline);
-        self.seniorities.insert(&badger.seniority);
-        self.clients.insert(&badger.client);
-        self.teams.insert(&badger.team);
+
+        for (column, value) in &record.attributes {
+            self.histograms
+                .entry(column.clone())
+                .or_insert_with(Histogram::new)
+                .insert(value.clone());
+        }
     }
 }
 
-// Fitness score itself, lower is better
-
-fn fitness(solution: &Vec<usize>, badgers: &Vec<Badger>, ideal: &Profile) -> f64 {
+// Fitness score itself, lower is better: sum of per-attribute imbalance,
+// each weighted by `schema`, plus the group size deviation, which carries
+// a fixed weight of 10.0.
+fn fitness(solution: &GroupAssignment, records: &[Record], ideal: &Profile, schema: &Schema) -> f64 {
     let mut profiles: HashMap<usize, Profile> = HashMap::new();
 
-    for i in 0..solution.len() {
-        profiles
-            .entry(solution[i])
-            .or_insert(Profile::new())
-            .insert(&badgers[i])
+    for (i, &group) in solution.groups.iter().enumerate() {
+        profiles.entry(group).or_insert_with(Profile::new).insert(&records[i]);
     }
 
     profiles
         .values()
         .map(|group| {
             let size = (ideal.count - group.count).abs();
+            let mut score = 10.0 * size;
 
-            let gender = ideal.genders.diff(&group.genders);
-            let discipline = ideal.disciplines.diff(&group.disciplines);
-            let seniority = ideal.seniorities.diff(&group.seniorities);
-            let client = ideal.clients.diff(&group.clients);
-            let team = ideal.teams.diff(&group.teams);
+            for (column, ideal_hist) in &ideal.histograms {
+                let diff = match group.histograms.get(column) {
+                    Some(group_hist) => ideal_hist.diff(group_hist),
+                    None => ideal_hist.counts.len() as f64,
+                };
+
+                score += schema.weight(column) * diff;
+            }
 
-            10.0 * size + 6.0 * gender + 3.0 * discipline + seniority + 2.0 + client + team
+            score
         })
+        .sum()
+}
+
+// Multi-objective optimization (SPEA2)
+//
+// Instead of collapsing group size and every balance attribute into one
+// weighted-sum score, this mode keeps them as separate objectives and
+// lets the engine maintain a Pareto front of trade-offs for organizers to
+// choose among.
+
+// Same per-group measurements as `fitness`, but kept as separate
+// objectives (summed per group) instead of combined with weights.
+fn objectives(solution: &GroupAssignment, records: &[Record], ideal: &Profile) -> Vec<f64> {
+    let mut profiles: HashMap<usize, Profile> = HashMap::new();
+
+    for (i, &group) in solution.groups.iter().enumerate() {
+        profiles.entry(group).or_insert_with(Profile::new).insert(&records[i]);
+    }
+
+    let columns: Vec<&String> = ideal.histograms.keys().collect();
+    let mut totals = vec![0.0; columns.len() + 1];
+
+    for group in profiles.values() {
+        totals[0] += (ideal.count - group.count).abs();
+
+        for (idx, column) in columns.iter().enumerate() {
+            let diff = match group.histograms.get(*column) {
+                Some(group_hist) => ideal.histograms[*column].diff(group_hist),
+                None => ideal.histograms[*column].counts.len() as f64,
+            };
+
+            totals[idx + 1] += diff;
+        }
+    }
+
+    totals
+}
+
+// Pareto dominance: `a` dominates `b` if it is no worse on every objective
+// and strictly better on at least one. Lower objective values are better.
+fn dominates(a: &[f64], b: &[f64]) -> bool {
+    let mut strictly_better = false;
+
+    for (x, y) in a.iter().zip(b) {
+        if x > y {
+            return false;
+        }
+        if x < y {
+            strictly_better = true;
+        }
+    }
+
+    strictly_better
+}
+
+fn euclidean_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y).powi(2))
         .sum::<f64>()
+        .sqrt()
+}
+
+// A genotype together with its objective values, as tracked by SPEA2's
+// population and archive.
+struct Spea2Individual {
+    genotype: GroupAssignment,
+    objectives: Vec<f64>,
+}
+
+// SPEA2 fitness (lower is better) for every member of `combined`
+// (population + archive): strength S(i) is the number of individuals i
+// dominates; raw fitness R(i) is the sum of S(j) over every j that
+// dominates i (so non-dominated individuals get R = 0); density D(i) is
+// 1 / (distance to the k-th nearest neighbour in objective space + 2).
+// Final fitness F(i) = R(i) + D(i).
+fn spea2_fitness(combined: &[Spea2Individual]) -> Vec<f64> {
+    let n = combined.len();
+    let k = ((n as f64).sqrt().round() as usize).max(1).min(n.saturating_sub(1).max(1));
+
+    let strength: Vec<usize> = combined
+        .iter()
+        .map(|i| {
+            combined
+                .iter()
+                .filter(|j| dominates(&i.objectives, &j.objectives))
+                .count()
+        })
+        .collect();
+
+    (0..n)
+        .map(|i| {
+            let raw: f64 = (0..n)
+                .filter(|&j| j != i && dominates(&combined[j].objectives, &combined[i].objectives))
+                .map(|j| strength[j] as f64)
+                .sum();
+
+            let mut distances: Vec<f64> = (0..n)
+                .filter(|&j| j != i)
+                .map(|j| euclidean_distance(&combined[i].objectives, &combined[j].objectives))
+                .collect();
+            distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let density = 1.0 / (distances.get(k - 1).copied().unwrap_or(0.0) + 2.0);
+
+            raw + density
+        })
+        .collect()
+}
+
+// Distance from `archive[idx]` to its nearest neighbour in the archive.
+fn nearest_distance(archive: &[Spea2Individual], idx: usize) -> f64 {
+    archive
+        .iter()
+        .enumerate()
+        .filter(|(j, _)| *j != idx)
+        .map(|(_, other)| euclidean_distance(&archive[idx].objectives, &other.objectives))
+        .fold(f64::INFINITY, f64::min)
+}
+
+// Builds the next archive out of the current population and archive:
+// every non-dominated individual (fitness < 1.0, i.e. R = 0) is kept; if
+// there are too many, the one closest to its nearest neighbour is
+// discarded repeatedly until the archive fits; if there are too few, it
+// is topped up with the best dominated individuals by SPEA2 fitness.
+fn environmental_selection(combined: Vec<Spea2Individual>, archive_size: usize) -> Vec<Spea2Individual> {
+    let fitness = spea2_fitness(&combined);
+
+    let mut scored: Vec<(f64, Spea2Individual)> = fitness.into_iter().zip(combined).collect();
+    scored.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+
+    let non_dominated_count = scored.iter().take_while(|(f, _)| *f < 1.0).count();
+
+    if non_dominated_count <= archive_size {
+        scored.truncate(archive_size.min(scored.len()));
+        return scored.into_iter().map(|(_, individual)| individual).collect();
+    }
+
+    let mut archive: Vec<Spea2Individual> = scored
+        .into_iter()
+        .take(non_dominated_count)
+        .map(|(_, individual)| individual)
+        .collect();
+
+    while archive.len() > archive_size {
+        let closest = (0..archive.len())
+            .min_by(|&a, &b| {
+                nearest_distance(&archive, a)
+                    .partial_cmp(&nearest_distance(&archive, b))
+                    .unwrap()
+            })
+            .unwrap();
+
+        archive.remove(closest);
+    }
+
+    archive
+}
+
+// Drives a population + archive through SPEA2 generations. Breeding
+// parents are drawn from the archive rather than the raw population.
+struct Spea2<O: Fn(&GroupAssignment) -> Vec<f64>> {
+    pub population: Vec<GroupAssignment>,
+    pub archive: Vec<Spea2Individual>,
+    pub archive_size: usize,
+    pub crossover_rate: f64,
+    pub mutation_rate: f64,
+    pub objectives: O,
+}
+
+impl<O: Fn(&GroupAssignment) -> Vec<f64> + Sync> Spea2<O> {
+    fn new(population_size: usize, archive_size: usize, ngroups: usize, nbadgers: usize, objectives: O) -> Self {
+        let mut rng = rand::thread_rng();
+        let seed = GroupAssignment::new(vec![0; nbadgers], ngroups);
+        let population: Vec<GroupAssignment> = (0..population_size).map(|_| seed.random(&mut rng)).collect();
+
+        Spea2 {
+            population,
+            archive: Vec::new(),
+            archive_size,
+            crossover_rate: 0.85,
+            mutation_rate: 0.15,
+            objectives,
+        }
+    }
+
+    pub fn next_gen(self) -> Self {
+        let size = self.population.len();
+        let objectives = &self.objectives;
+
+        let evaluated: Vec<Spea2Individual> = self
+            .population
+            .par_iter()
+            .map(|genotype| Spea2Individual {
+                genotype: genotype.clone(),
+                objectives: objectives(genotype),
+            })
+            .collect();
+
+        let mut combined = self.archive;
+        combined.extend(evaluated);
+
+        let archive = environmental_selection(combined, self.archive_size);
+
+        // Breeding: parents are drawn from the archive only.
+        let mut population: Vec<GroupAssignment> = Vec::with_capacity(size);
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..size {
+            let mother = &archive[rng.gen_range(0, archive.len())].genotype;
+
+            let mut child = if rng.gen_bool(self.crossover_rate) {
+                let father = &archive[rng.gen_range(0, archive.len())].genotype;
+                mother.crossover(father, &mut rng)
+            } else {
+                mother.clone()
+            };
+
+            child = child.mutate(self.mutation_rate, &mut rng);
+
+            population.push(child);
+        }
+
+        Spea2 {
+            population,
+            archive,
+            ..self
+        }
+    }
 }
 
 fn main() {
-    match read_badgers() {
+    match read_records() {
         Err(err) => {
-            println!("Could not read badgers: {}", err);
+            println!("Could not read records: {}", err);
             process::exit(1);
         }
-        Ok(badgers) => {
+        Ok(records) => {
+            let columns: Vec<String> = records
+                .first()
+                .map(|record| record.attributes.iter().map(|(column, _)| column.clone()).collect())
+                .unwrap_or_default();
+            let schema = Schema::new(&columns);
+
             let mut ideal = Profile::new();
-            for badger in badgers.iter() {
-                ideal.insert(badger)
+            for record in &records {
+                ideal.insert(record)
             }
-            ideal.count = badgers.len() as f64 / 9.0;
+            ideal.count = records.len() as f64 / 9.0;
 
             // Initial generation
 
-            let mut generation = Generation::new(150, 9, badgers.len(), |solution| {
-                fitness(solution, &badgers, &ideal)
-            });
+            let constraints = Constraints::new(records.len() / 9, 1);
+            let seed = GroupAssignment::new(vec![0; records.len()], 9);
+
+            let mut generation = Generation::new(
+                150,
+                seed,
+                |solution| fitness(solution, &records, &ideal, &schema),
+                Truncation { survival_rate: 0.2 },
+                constraints,
+            )
+            .with_cache();
 
             // metaheuristic parameters
 
             generation.crossover_rate = 0.5;
             generation.mutation_rate = 0.5;
-            generation.survival_rate = 0.2;
+            generation.mutation_floor = 0.1;
+            generation.mutation_ceiling = 0.8;
+            generation.convergence_window = 20;
+            generation.convergence_epsilon = 1e-3;
 
             // Optimisation loop
+            //
+            // Each population is scored exactly once per generation: the
+            // same `scored` vector drives the printed best, `track`'s
+            // convergence check, and `next_gen`'s breeding.
+            let mut scored = generation.scored();
+
             for i in 0..300 {
-                let fittest = generation.fittest();
-                let best = &fittest[0];
-                let score = fitness(best, &badgers, &ideal);
+                let (score, best) = &scored[0];
+
+                println!("Gen {:>4} - best: {:.5} - {:?}", i, score, best.groups);
 
-                println!("Gen {:>4} - best: {:.5} - {:?}", i, score, best);
+                if generation.track(*score, best) {
+                    println!("Converged after {} generations", i);
+                    break;
+                }
 
-                generation = generation.next_gen();
+                generation = generation.next_gen(&scored);
+                scored = generation.scored();
             }
 
             // Print results
 
-            let fittest = generation.fittest();
-            let best = &fittest[0];
+            let (_, best) = &scored[0];
 
-            let mut tagged: Vec<_> = best.into_iter().zip(&badgers).collect();
-            tagged.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let mut tagged: Vec<_> = best.groups.iter().zip(&records).collect();
+            tagged.sort_by_key(|(group, _)| *group);
 
             let mut group: i32 = 0;
-            for (g, badger) in tagged {
+            for (g, record) in tagged {
                 if *g as i32 > group {
                     group += 1;
                     println!("= Group #{}", group);
                 }
-                println!("{}", badger);
+                println!("{}", record);
+            }
+
+            // Multi-objective run (SPEA2): same problem, but the balance
+            // objectives are kept separate instead of weighted into one
+            // score, so organizers can pick among Pareto-optimal trade-offs.
+
+            let mut spea2 = Spea2::new(150, 30, 9, records.len(), |solution| {
+                objectives(solution, &records, &ideal)
+            });
+
+            for _ in 0..300 {
+                spea2 = spea2.next_gen();
+            }
+
+            println!("\n= Pareto archive ({} solutions)", spea2.archive.len());
+            for (n, individual) in spea2.archive.iter().enumerate() {
+                println!("#{:>2} objectives: {:?}", n, individual.objectives);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod constraints_tests {
+    use super::*;
+
+    #[test]
+    fn validate_counts_together_apart_and_size_violations() {
+        let mut constraints = Constraints::new(2, 0);
+        constraints.together = vec![(0, 1)];
+        constraints.apart = vec![(2, 3)];
+
+        // Group 0 (indices 0, 2, 3) has 3 people against a group_size of 2;
+        // group 1 (index 1) has 1. 0 and 1 are split apart despite
+        // `together`, and 2 and 3 end up together despite `apart`.
+        let groups = GroupAssignment::new(vec![0, 1, 0, 0], 2);
+
+        assert_eq!(constraints.validate(&groups), 4);
+    }
+
+    #[test]
+    fn validate_is_zero_for_a_feasible_assignment() {
+        let mut constraints = Constraints::new(2, 0);
+        constraints.together = vec![(0, 1)];
+        constraints.apart = vec![(0, 2)];
+
+        let groups = GroupAssignment::new(vec![0, 0, 1, 1], 2);
+
+        assert_eq!(constraints.validate(&groups), 0);
+    }
+
+    #[test]
+    fn repair_terminates_and_brings_every_group_within_tolerance() {
+        let constraints = Constraints::new(2, 0);
+        // Every person starts in group 0: maximally overfull.
+        let mut groups = GroupAssignment::new(vec![0; 8], 4);
+
+        constraints.repair(&mut groups);
+
+        let sizes = constraints.group_sizes(&groups.groups);
+        for group in 0..4 {
+            let count = sizes.get(&group).copied().unwrap_or(0) as i64;
+            let deviation = (count - constraints.group_size as i64).unsigned_abs();
+            assert!(
+                deviation <= constraints.size_tolerance as u64,
+                "group {} has {} members",
+                group,
+                count
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod dominates_tests {
+    use super::*;
+
+    #[test]
+    fn strictly_better_on_every_objective_dominates() {
+        assert!(dominates(&[1.0, 1.0], &[2.0, 2.0]));
+    }
+
+    #[test]
+    fn worse_on_any_objective_does_not_dominate() {
+        assert!(!dominates(&[1.0, 3.0], &[2.0, 2.0]));
+    }
+
+    #[test]
+    fn equal_on_every_objective_does_not_dominate() {
+        assert!(!dominates(&[1.0, 1.0], &[1.0, 1.0]));
+    }
+
+    #[test]
+    fn better_on_one_and_equal_on_the_rest_dominates() {
+        assert!(dominates(&[1.0, 1.0], &[1.0, 2.0]));
+    }
+}